@@ -17,6 +17,135 @@ bitflags! {
 const STACK: u16 = 0x0100;
 const STACK_RESET: u8 = 0xfd;
 
+/// Bumped whenever `save_state`'s layout changes, so `load_state` can reject
+/// a blob from an incompatible version instead of silently misreading it.
+const SAVE_STATE_VERSION: u8 = 1;
+
+/// Battery-backed cartridge RAM window persisted by `save_battery_ram`.
+const BATTERY_RAM_START: u16 = 0x6000;
+const BATTERY_RAM_END: u16 = 0x7FFF;
+
+/// Errors returned by `CPU::load_state`/`load_battery_ram` for a blob that
+/// can't be trusted rather than panicking on malformed input.
+#[derive(Debug)]
+pub enum StateError {
+    UnsupportedVersion(u8),
+    Truncated,
+}
+
+impl std::fmt::Display for StateError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            StateError::UnsupportedVersion(version) => {
+                write!(f, "unsupported save state version {}", version)
+            }
+            StateError::Truncated => write!(f, "save state buffer has the wrong length"),
+        }
+    }
+}
+
+impl std::error::Error for StateError {}
+
+/// Base cycle cost of every opcode, indexed by opcode byte. Taken from the
+/// standard 6502 timing table; unofficial/unimplemented opcodes are filled
+/// with 2 as a placeholder since they are never reached (`step` panics on
+/// them). Page-crossing and branch penalties are added on top of
+/// this in `step`.
+#[rustfmt::skip]
+const INST_CYCLES: [u8; 256] = [
+    7, 6, 2, 2, 2, 3, 5, 2, 3, 2, 2, 2, 2, 4, 6, 2,
+    2, 5, 2, 2, 2, 4, 6, 2, 2, 4, 2, 2, 2, 4, 7, 2,
+    6, 6, 2, 2, 3, 3, 5, 2, 4, 2, 2, 2, 4, 4, 6, 2,
+    2, 5, 2, 2, 2, 4, 6, 2, 2, 4, 2, 2, 2, 4, 7, 2,
+    6, 6, 2, 2, 2, 3, 5, 2, 3, 2, 2, 2, 3, 4, 6, 2,
+    2, 5, 2, 2, 2, 4, 6, 2, 2, 4, 2, 2, 2, 4, 7, 2,
+    6, 6, 2, 2, 2, 3, 5, 2, 4, 2, 2, 2, 5, 4, 6, 2,
+    2, 5, 2, 2, 2, 4, 6, 2, 2, 4, 2, 2, 2, 4, 7, 2,
+    2, 6, 2, 2, 3, 3, 3, 2, 2, 2, 2, 2, 4, 4, 4, 2,
+    2, 6, 2, 2, 4, 4, 4, 2, 2, 5, 2, 2, 2, 5, 2, 2,
+    2, 6, 2, 2, 3, 3, 3, 2, 2, 2, 2, 2, 4, 4, 4, 2,
+    2, 5, 2, 2, 4, 4, 4, 2, 2, 4, 2, 2, 4, 4, 4, 2,
+    2, 6, 2, 2, 3, 3, 5, 2, 2, 2, 2, 2, 4, 4, 6, 2,
+    2, 5, 2, 2, 2, 4, 6, 2, 2, 4, 2, 2, 2, 4, 7, 2,
+    2, 6, 2, 2, 3, 3, 5, 2, 2, 2, 2, 2, 4, 4, 6, 2,
+    2, 5, 2, 2, 2, 4, 6, 2, 2, 4, 2, 2, 2, 4, 7, 2,
+];
+
+/// Anything that can be addressed by the CPU's 16-bit address bus.
+///
+/// Today `RamBus` is the only implementation, but this is the seam a
+/// cartridge mapper or PPU/APU register block will plug into: reads and
+/// writes to $2000-$3FFF and friends can be intercepted without `CPU`
+/// knowing anything changed.
+pub trait Bus {
+    fn read(&self, addr: u16) -> u8;
+    fn write(&mut self, addr: u16, data: u8);
+
+    fn read_u16(&self, pos: u16) -> u16 {
+        let lo = self.read(pos) as u16;
+        let hi = self.read(pos.wrapping_add(1)) as u16;
+        (hi << 8) | lo
+    }
+
+    fn write_u16(&mut self, pos: u16, data: u16) {
+        let hi = (data >> 8) as u8;
+        let lo = (data & 0xff) as u8;
+        self.write(pos, lo);
+        self.write(pos.wrapping_add(1), hi);
+    }
+
+    /// Dumps the full addressable memory image for a `CPU::save_state`.
+    fn snapshot(&self) -> Vec<u8>;
+
+    /// Restores a memory image previously produced by `snapshot`.
+    fn restore(&mut self, data: &[u8]);
+}
+
+/// A flat 64K RAM `Bus` with no mirroring or mapped regions, preserving the
+/// behavior `CPU` used to get from its own `memory` array.
+pub struct RamBus {
+    memory: [u8; 0x10000],
+}
+
+impl RamBus {
+    pub fn new() -> Self {
+        RamBus { memory: [0; 0x10000] }
+    }
+}
+
+impl Bus for RamBus {
+    fn read(&self, addr: u16) -> u8 {
+        self.memory[addr as usize]
+    }
+
+    fn write(&mut self, addr: u16, data: u8) {
+        self.memory[addr as usize] = data;
+    }
+
+    fn snapshot(&self) -> Vec<u8> {
+        self.memory.to_vec()
+    }
+
+    fn restore(&mut self, data: &[u8]) {
+        self.memory.copy_from_slice(data);
+    }
+}
+
+/// Shared by the memory and register forms of INC/DEC (INC, INX, INY, DEC,
+/// DEX, DEY) so the increment/decrement and flag update logic lives in one
+/// place.
+fn increment(value: &mut u8, status: &mut CpuFlags) {
+    *value = value.wrapping_add(1);
+    status.set(CpuFlags::ZERO, *value == 0);
+    status.set(CpuFlags::NEGATIVE, *value & 0b1000_0000 != 0);
+}
+
+fn decrement(value: &mut u8, status: &mut CpuFlags) {
+    *value = value.wrapping_sub(1);
+    status.set(CpuFlags::ZERO, *value == 0);
+    status.set(CpuFlags::NEGATIVE, *value & 0b1000_0000 != 0);
+}
+
 pub struct CPU {
     pub register_a: u8,
     pub register_x: u8,
@@ -24,7 +153,11 @@ pub struct CPU {
     pub status: CpuFlags,
     pub program_counter: u16,
     pub stack_pointer: u8,
-    pub memory: [u8; 0xFFFF],
+    pub bus: Box<dyn Bus>,
+    pub cycles: u64,
+    /// Set by an external device (e.g. a PPU at vblank) to request an NMI;
+    /// polled and cleared at the top of `step`.
+    pub nmi_pending: bool,
 }
 
 #[derive(Debug)]
@@ -51,64 +184,64 @@ impl CPU {
             stack_pointer: STACK_RESET,
             status: CpuFlags::from_bits_truncate(0b00100100),
             program_counter: 0,
-            memory: [0; 0xFFFF],
+            bus: Box::new(RamBus::new()),
+            cycles: 0,
+            nmi_pending: false,
         }
     }
 
     fn mem_read(&self, addr: u16) -> u8 {
-        self.memory[addr as usize]
+        self.bus.read(addr)
     }
 
     fn mem_write(&mut self, addr: u16, data: u8) {
-        self.memory[addr as usize] = data;
+        self.bus.write(addr, data);
     }
 
     fn mem_read_u16(&self, pos: u16) -> u16 {
-        let lo = self.mem_read(pos) as u16;
-        let hi = self.mem_read(pos + 1) as u16;
-        (hi << 8) | lo
+        self.bus.read_u16(pos)
     }
 
     fn mem_write_u16(&mut self, pos: u16, data: u16) {
-        let hi = (data >> 8) as u8;
-        let lo = (data & 0xff) as u8;
-        self.mem_write(pos, lo);
-        self.mem_write(pos + 1, hi);
+        self.bus.write_u16(pos, data);
     }
 
-    fn lda(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+    fn lda(&mut self, mode: &AddressingMode) -> bool {
+        let (addr, page_crossed) = self.get_operand_address(mode);
         let value = self.mem_read(addr);
         self.register_a = value;
         self.update_zero_and_negative_falgs(self.register_a);
+        page_crossed
     }
 
-    fn ldx(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+    fn ldx(&mut self, mode: &AddressingMode) -> bool {
+        let (addr, page_crossed) = self.get_operand_address(mode);
         let value = self.mem_read(addr);
         self.register_x = value;
         self.update_zero_and_negative_falgs(self.register_x);
+        page_crossed
     }
 
-    fn ldy(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+    fn ldy(&mut self, mode: &AddressingMode) -> bool {
+        let (addr, page_crossed) = self.get_operand_address(mode);
         let value = self.mem_read(addr);
         self.register_y = value;
         self.update_zero_and_negative_falgs(self.register_y);
+        page_crossed
     }
 
     fn sta(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+        let (addr, _) = self.get_operand_address(mode);
         self.mem_write(addr, self.register_a);
     }
 
     fn stx(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+        let (addr, _) = self.get_operand_address(mode);
         self.mem_write(addr, self.register_x);
     }
 
     fn sty(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+        let (addr, _) = self.get_operand_address(mode);
         self.mem_write(addr, self.register_y);
     }
 
@@ -133,49 +266,78 @@ impl CPU {
     }
 
     fn inc(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
-        let value = self.mem_read(addr).wrapping_add(1);
+        let (addr, _) = self.get_operand_address(mode);
+        let mut value = self.mem_read(addr);
+        increment(&mut value, &mut self.status);
         self.mem_write(addr, value);
-        self.update_zero_and_negative_falgs(value);
     }
 
     fn inx(&mut self) {
-        self.update_zero_and_negative_falgs(self.register_x.wrapping_add(1));
+        increment(&mut self.register_x, &mut self.status);
     }
 
     fn iny(&mut self) {
-        self.update_zero_and_negative_falgs(self.register_y.wrapping_add(1));
+        increment(&mut self.register_y, &mut self.status);
     }
 
-    fn and(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+    fn dec(&mut self, mode: &AddressingMode) {
+        let (addr, _) = self.get_operand_address(mode);
+        let mut value = self.mem_read(addr);
+        decrement(&mut value, &mut self.status);
+        self.mem_write(addr, value);
+    }
+
+    fn dex(&mut self) {
+        decrement(&mut self.register_x, &mut self.status);
+    }
+
+    fn dey(&mut self) {
+        decrement(&mut self.register_y, &mut self.status);
+    }
+
+    fn and(&mut self, mode: &AddressingMode) -> bool {
+        let (addr, page_crossed) = self.get_operand_address(mode);
         self.register_a &= self.mem_read(addr);
         self.update_zero_and_negative_falgs(self.register_a);
+        page_crossed
     }
 
-    fn ora(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+    fn ora(&mut self, mode: &AddressingMode) -> bool {
+        let (addr, page_crossed) = self.get_operand_address(mode);
         self.register_a |= self.mem_read(addr);
         self.update_zero_and_negative_falgs(self.register_a);
+        page_crossed
     }
 
-    fn eor(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+    fn eor(&mut self, mode: &AddressingMode) -> bool {
+        let (addr, page_crossed) = self.get_operand_address(mode);
         self.register_a ^= self.mem_read(addr);
         self.update_zero_and_negative_falgs(self.register_a);
+        page_crossed
     }
 
-    fn adc(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
-        self.register_a = self.add_to_register_a(self.mem_read(addr));
+    fn adc(&mut self, mode: &AddressingMode) -> bool {
+        let (addr, page_crossed) = self.get_operand_address(mode);
+        let value = self.mem_read(addr);
+        self.register_a = if self.status.contains(CpuFlags::DECIMAL_MODE) {
+            self.add_to_register_a_decimal(value)
+        } else {
+            self.add_to_register_a(value)
+        };
         self.update_zero_and_negative_falgs(self.register_a);
+        page_crossed
     }
 
-    fn sbc(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
-        let neg_value = !self.mem_read(addr) + 1;
-        self.register_a = self.add_to_register_a(neg_value);
+    fn sbc(&mut self, mode: &AddressingMode) -> bool {
+        let (addr, page_crossed) = self.get_operand_address(mode);
+        let value = self.mem_read(addr);
+        self.register_a = if self.status.contains(CpuFlags::DECIMAL_MODE) {
+            self.sub_from_register_a_decimal(value)
+        } else {
+            self.add_to_register_a(value ^ 0xff)
+        };
         self.update_zero_and_negative_falgs(self.register_a);
+        page_crossed
     }
 
     fn asl_accumulator(&mut self) {
@@ -186,7 +348,7 @@ impl CPU {
     }
 
     fn asl(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+        let (addr, _) = self.get_operand_address(mode);
         let shifted = (self.mem_read(addr) as u16) << 1;
         self.status.set(CpuFlags::CARRY, shifted > 0xff);
         self.mem_write(addr, shifted as u8);
@@ -200,7 +362,7 @@ impl CPU {
     }
 
     fn lsr(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+        let (addr, _) = self.get_operand_address(mode);
         let value = self.mem_read(addr);
         self.status.set(CpuFlags::CARRY, value & 1 == 1);
         self.mem_write(addr, value >> 1);
@@ -219,7 +381,7 @@ impl CPU {
     }
 
     fn rol(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+        let (addr, _) = self.get_operand_address(mode);
         let old_carry = self.status.contains(CpuFlags::CARRY);
         let mut shifted = (self.register_a as u16) << 1;
         self.status.set(CpuFlags::CARRY, shifted > 0xff);
@@ -241,7 +403,7 @@ impl CPU {
     }
 
     fn ror(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+        let (addr, _) = self.get_operand_address(mode);
         let old_carry = self.status.contains(CpuFlags::CARRY);
         let mut value = self.mem_read(addr);
         self.status.set(CpuFlags::CARRY, value & 1 == 1);
@@ -252,25 +414,214 @@ impl CPU {
         self.update_zero_and_negative_falgs(self.mem_read(addr));
     }
 
-    fn branch(&mut self, condition: bool) {
+    /// Returns the number of extra cycles the branch cost: 0 if not taken, 1
+    /// if taken, 2 if taken to a different page than the following instruction.
+    fn branch(&mut self, condition: bool) -> u8 {
         if !condition {
-            return;
+            return 0;
         }
         let offset = self.mem_read(self.program_counter);
-        self.program_counter = self
-            .program_counter
-            .wrapping_add(1)
-            .wrapping_add(offset as u16);
+        let next_pc = self.program_counter.wrapping_add(1);
+        let target = next_pc.wrapping_add(offset as i8 as u16);
+        self.program_counter = target;
+        if next_pc & 0xFF00 != target & 0xFF00 {
+            2
+        } else {
+            1
+        }
     }
 
     fn bit(&mut self, mode: &AddressingMode) {
-        let addr = self.get_operand_address(mode);
+        let (addr, _) = self.get_operand_address(mode);
         let value = self.mem_read(addr);
         self.status.set(CpuFlags::OVERFLOW, value & 0b0100_0000 != 0);
         self.status.set(CpuFlags::NEGATIVE, value & 0b1000_0000 != 0);
         self.status.set(CpuFlags::ZERO, value & self.register_a == 0);
     }
 
+    fn compare(&mut self, mode: &AddressingMode, register: u8) -> bool {
+        let (addr, page_crossed) = self.get_operand_address(mode);
+        let value = self.mem_read(addr);
+        self.status.set(CpuFlags::CARRY, register >= value);
+        self.update_zero_and_negative_falgs(register.wrapping_sub(value));
+        page_crossed
+    }
+
+    fn cmp(&mut self, mode: &AddressingMode) -> bool {
+        self.compare(mode, self.register_a)
+    }
+
+    fn cpx(&mut self, mode: &AddressingMode) -> bool {
+        self.compare(mode, self.register_x)
+    }
+
+    fn cpy(&mut self, mode: &AddressingMode) -> bool {
+        self.compare(mode, self.register_y)
+    }
+
+    fn clc(&mut self) {
+        self.status.remove(CpuFlags::CARRY);
+    }
+
+    fn sec(&mut self) {
+        self.status.insert(CpuFlags::CARRY);
+    }
+
+    fn cld(&mut self) {
+        self.status.remove(CpuFlags::DECIMAL_MODE);
+    }
+
+    fn sed(&mut self) {
+        self.status.insert(CpuFlags::DECIMAL_MODE);
+    }
+
+    fn cli(&mut self) {
+        self.status.remove(CpuFlags::INTERRUPT_DISABLE);
+    }
+
+    fn sei(&mut self) {
+        self.status.insert(CpuFlags::INTERRUPT_DISABLE);
+    }
+
+    fn clv(&mut self) {
+        self.status.remove(CpuFlags::OVERFLOW);
+    }
+
+    fn tsx(&mut self) {
+        self.register_x = self.stack_pointer;
+        self.update_zero_and_negative_falgs(self.register_x);
+    }
+
+    fn txs(&mut self) {
+        self.stack_pointer = self.register_x;
+    }
+
+    fn stack_push(&mut self, data: u8) {
+        self.mem_write(STACK + self.stack_pointer as u16, data);
+        self.stack_pointer = self.stack_pointer.wrapping_sub(1);
+    }
+
+    fn stack_push_u16(&mut self, data: u16) {
+        self.stack_push((data >> 8) as u8);
+        self.stack_push((data & 0xff) as u8);
+    }
+
+    fn stack_pop(&mut self) -> u8 {
+        self.stack_pointer = self.stack_pointer.wrapping_add(1);
+        self.mem_read(STACK + self.stack_pointer as u16)
+    }
+
+    fn stack_pop_u16(&mut self) -> u16 {
+        let lo = self.stack_pop() as u16;
+        let hi = self.stack_pop() as u16;
+        (hi << 8) | lo
+    }
+
+    fn pha(&mut self) {
+        self.stack_push(self.register_a);
+    }
+
+    fn pla(&mut self) {
+        self.register_a = self.stack_pop();
+        self.update_zero_and_negative_falgs(self.register_a);
+    }
+
+    /// Pushes status with the B flag (and the always-1 bit 5) set, the quirk
+    /// that distinguishes a software PHP/BRK snapshot from one pushed by a
+    /// real NMI/IRQ, which push B clear.
+    fn php(&mut self) {
+        let mut flags = self.status;
+        flags.insert(CpuFlags::BREAK);
+        flags.insert(CpuFlags::BREAK2);
+        self.stack_push(flags.bits());
+    }
+
+    fn plp(&mut self) {
+        self.status = CpuFlags::from_bits_truncate(self.stack_pop());
+        self.status.remove(CpuFlags::BREAK);
+        self.status.insert(CpuFlags::BREAK2);
+    }
+
+    fn jmp_absolute(&mut self) {
+        self.program_counter = self.mem_read_u16(self.program_counter);
+    }
+
+    /// JMP ($xxFF) famously fetches its high byte from $xx00 instead of
+    /// wrapping into the next page, a hardware bug real NES software
+    /// sometimes dodges by padding indirect vectors off a page boundary.
+    fn jmp_indirect(&mut self) {
+        let ptr = self.mem_read_u16(self.program_counter);
+        let target = if ptr & 0x00ff == 0x00ff {
+            let lo = self.mem_read(ptr);
+            let hi = self.mem_read(ptr & 0xff00);
+            (hi as u16) << 8 | (lo as u16)
+        } else {
+            self.mem_read_u16(ptr)
+        };
+        self.program_counter = target;
+    }
+
+    fn jsr(&mut self) {
+        self.stack_push_u16(self.program_counter.wrapping_add(1));
+        self.program_counter = self.mem_read_u16(self.program_counter);
+    }
+
+    fn rts(&mut self) {
+        self.program_counter = self.stack_pop_u16().wrapping_add(1);
+    }
+
+    fn rti(&mut self) {
+        self.status = CpuFlags::from_bits_truncate(self.stack_pop());
+        self.status.remove(CpuFlags::BREAK);
+        self.status.insert(CpuFlags::BREAK2);
+        self.program_counter = self.stack_pop_u16();
+    }
+
+    /// BRK pushes the return address past its signature byte (`program_counter`
+    /// has already advanced over the opcode itself by the time `step` dispatches
+    /// here), pushes status with B set, and jumps through the IRQ/BRK vector at
+    /// $FFFE - the same vector `irq` uses, since on real hardware the two are
+    /// only distinguished by the pushed B flag.
+    fn brk(&mut self) {
+        self.stack_push_u16(self.program_counter.wrapping_add(1));
+        let mut flags = self.status;
+        flags.insert(CpuFlags::BREAK);
+        flags.insert(CpuFlags::BREAK2);
+        self.stack_push(flags.bits());
+        self.status.insert(CpuFlags::INTERRUPT_DISABLE);
+        self.program_counter = self.mem_read_u16(0xFFFE);
+    }
+
+    /// Non-maskable interrupt: pushes the current `program_counter` and status
+    /// with B clear (distinguishing it from a software BRK), then jumps through
+    /// the NMI vector at $FFFA. Unlike `irq`, this always fires.
+    fn nmi(&mut self) {
+        self.stack_push_u16(self.program_counter);
+        let mut flags = self.status;
+        flags.remove(CpuFlags::BREAK);
+        flags.insert(CpuFlags::BREAK2);
+        self.stack_push(flags.bits());
+        self.status.insert(CpuFlags::INTERRUPT_DISABLE);
+        self.program_counter = self.mem_read_u16(0xFFFA);
+        self.cycles += 7;
+    }
+
+    /// Maskable interrupt request: the mirror image of `nmi`, but gated on
+    /// `INTERRUPT_DISABLE` and vectored through $FFFE like BRK.
+    pub fn irq(&mut self) {
+        if self.status.contains(CpuFlags::INTERRUPT_DISABLE) {
+            return;
+        }
+        self.stack_push_u16(self.program_counter);
+        let mut flags = self.status;
+        flags.remove(CpuFlags::BREAK);
+        flags.insert(CpuFlags::BREAK2);
+        self.stack_push(flags.bits());
+        self.status.insert(CpuFlags::INTERRUPT_DISABLE);
+        self.program_counter = self.mem_read_u16(0xFFFE);
+        self.cycles += 7;
+    }
+
     fn add_to_register_a(&mut self, data: u8) -> u8 {
         let sum = self.register_a as u16
             + data as u16
@@ -289,68 +640,183 @@ impl CPU {
         result
     }
 
+    /// BCD addition used by `adc` when `DECIMAL_MODE` is set: each nibble is
+    /// summed as a decimal digit and corrected by 6 (the gap between a
+    /// hex carry and a decimal one) whenever it overflows 9.
+    fn add_to_register_a_decimal(&mut self, data: u8) -> u8 {
+        let carry_in = if self.status.contains(CpuFlags::CARRY) {
+            1
+        } else {
+            0
+        };
+        let mut lo = (self.register_a & 0x0f) + (data & 0x0f) + carry_in;
+        let mut hi = (self.register_a >> 4) + (data >> 4);
+        if lo > 9 {
+            lo += 6;
+            hi += 1;
+        }
+        if hi > 9 {
+            hi += 6;
+            self.status.insert(CpuFlags::CARRY);
+        } else {
+            self.status.remove(CpuFlags::CARRY);
+        }
+        (hi << 4) | (lo & 0x0f)
+    }
+
+    /// BCD subtraction used by `sbc` when `DECIMAL_MODE` is set: the
+    /// mirror-image of `add_to_register_a_decimal`, borrowing 6 out of a
+    /// nibble that goes negative. `CARRY` means "no borrow", matching the
+    /// binary path.
+    fn sub_from_register_a_decimal(&mut self, data: u8) -> u8 {
+        let borrow_in: i16 = if self.status.contains(CpuFlags::CARRY) {
+            0
+        } else {
+            1
+        };
+        let a = self.register_a as i16;
+        let m = data as i16;
+
+        let mut lo = (a & 0x0f) - (m & 0x0f) - borrow_in;
+        if lo < 0 {
+            lo -= 6;
+        }
+        let mut hi = (a >> 4) - (m >> 4) + (lo >> 4);
+        if hi < 0 {
+            hi -= 6;
+        }
+
+        let binary_diff = a - m - borrow_in;
+        self.status.set(CpuFlags::CARRY, binary_diff >= 0);
+        (((hi << 4) | (lo & 0x0f)) & 0xff) as u8
+    }
+
     fn update_zero_and_negative_falgs(&mut self, result: u8) {
         self.status.set(CpuFlags::ZERO, result == 0);
         self.status.set(CpuFlags::NEGATIVE, result & 0b1000_0000 != 0);
     }
 
     pub fn run(&mut self) {
-        let ref opcodes: HashMap<u8, &'static opcodes::OpCode> = *opcodes::OPCODES_MAP;
-
         loop {
             let code = self.mem_read(self.program_counter);
-            self.program_counter += 1;
-            let program_counter_state = self.program_counter;
-
-            let opcode = opcodes
-                .get(&code)
-                .expect(&format!("OpCode {:x} is not recognized", code));
-
-            match code {
-                0x69 | 0x65 | 0x75 | 0x6d | 0x7d | 0x79 | 0x61 | 0x71 => self.adc(&opcode.mode),
-                0xe9 | 0xe5 | 0xf5 | 0xed | 0xfd | 0xf9 | 0xe1 | 0xf1 => self.sbc(&opcode.mode),
-                0xa9 | 0xa5 | 0xb5 | 0xad | 0xbd | 0xb9 | 0xa1 | 0xb1 => self.lda(&opcode.mode),
-                0x29 | 0x25 | 0x35 | 0x2d | 0x3d | 0x39 | 0x21 | 0x31 => self.and(&opcode.mode),
-                0x09 | 0x05 | 0x15 | 0x0d | 0x1d | 0x19 | 0x01 | 0x11 => self.ora(&opcode.mode),
-                0x49 | 0x45 | 0x55 | 0x4d | 0x5d | 0x59 | 0x41 | 0x51 => self.eor(&opcode.mode),
-                0x85 | 0x95 | 0x8d | 0x9d | 0x99 | 0x81 | 0x91 => self.sta(&opcode.mode),
-                0xa2 | 0xa6 | 0xb6 | 0xae | 0xbe => self.ldx(&opcode.mode),
-                0xa0 | 0xa4 | 0xb4 | 0xac | 0xbc => self.ldy(&opcode.mode),
-                0x06 | 0x16 | 0x0e | 0x1e => self.asl(&opcode.mode),
-                0x46 | 0x56 | 0x4e | 0x5e => self.lsr(&opcode.mode),
-                0x26 | 0x36 | 0x2e | 0x3e => self.rol(&opcode.mode),
-                0x66 | 0x76 | 0x6e | 0x7e => self.ror(&opcode.mode),
-                0xe6 | 0xf6 | 0xee | 0xfe => self.inc(&opcode.mode),
-                0x86 | 0x96 | 0x8e => self.stx(&opcode.mode),
-                0x84 | 0x94 | 0x8c => self.sty(&opcode.mode),
-                0x24 | 0x2c => self.bit(&opcode.mode),
-                0x0a => self.asl_accumulator(),
-                0x4a => self.lsr_accumulator(),
-                0x2a => self.rol_accumulator(),
-                0x6a => self.ror_accumulator(),
-                0xe8 => self.inx(),
-                0xc8 => self.iny(),
-                0xaa => self.tax(),
-                0x8a => self.txa(),
-                0xa8 => self.tay(),
-                0x98 => self.tya(),
-                0xb0 => self.branch(self.status.contains(CpuFlags::CARRY)),
-                0x90 => self.branch(!self.status.contains(CpuFlags::CARRY)),
-                0xf0 => self.branch(self.status.contains(CpuFlags::ZERO)),
-                0xd0 => self.branch(!self.status.contains(CpuFlags::ZERO)),
-                0x30 => self.branch(self.status.contains(CpuFlags::NEGATIVE)),
-                0x10 => self.branch(!self.status.contains(CpuFlags::NEGATIVE)),
-                0x70 => self.branch(self.status.contains(CpuFlags::OVERFLOW)),
-                0x50 => self.branch(!self.status.contains(CpuFlags::OVERFLOW)),
-                0xea => { /* nop */ }
-                0x00 => return,
-                _ => todo!(),
+            self.step();
+            if code == 0x00 {
+                break;
             }
+        }
+    }
 
-            if program_counter_state == self.program_counter {
-                self.program_counter += (opcode.len - 1) as u16;
+    /// Executes exactly one instruction and returns the number of cycles it
+    /// consumed (base cost from `INST_CYCLES` plus any page-crossing or
+    /// branch penalty), so a caller can interleave other subsystems between
+    /// instructions instead of only ever running to completion via `run`.
+    /// If `nmi_pending` is set, it services the NMI instead of executing an
+    /// instruction and returns 7.
+    pub fn step(&mut self) -> u8 {
+        if self.nmi_pending {
+            self.nmi_pending = false;
+            self.nmi();
+            return 7;
+        }
+
+        let ref opcodes: HashMap<u8, &'static opcodes::OpCode> = *opcodes::OPCODES_MAP;
+
+        let code = self.mem_read(self.program_counter);
+        self.program_counter += 1;
+        let program_counter_state = self.program_counter;
+
+        let opcode = opcodes
+            .get(&code)
+            .expect(&format!("OpCode {:x} is not recognized", code));
+
+        let mut extra_cycles: u8 = 0;
+
+        match code {
+            0x69 | 0x65 | 0x75 | 0x6d | 0x7d | 0x79 | 0x61 | 0x71 => {
+                extra_cycles += self.adc(&opcode.mode) as u8
+            }
+            0xe9 | 0xe5 | 0xf5 | 0xed | 0xfd | 0xf9 | 0xe1 | 0xf1 => {
+                extra_cycles += self.sbc(&opcode.mode) as u8
+            }
+            0xa9 | 0xa5 | 0xb5 | 0xad | 0xbd | 0xb9 | 0xa1 | 0xb1 => {
+                extra_cycles += self.lda(&opcode.mode) as u8
+            }
+            0x29 | 0x25 | 0x35 | 0x2d | 0x3d | 0x39 | 0x21 | 0x31 => {
+                extra_cycles += self.and(&opcode.mode) as u8
             }
+            0x09 | 0x05 | 0x15 | 0x0d | 0x1d | 0x19 | 0x01 | 0x11 => {
+                extra_cycles += self.ora(&opcode.mode) as u8
+            }
+            0x49 | 0x45 | 0x55 | 0x4d | 0x5d | 0x59 | 0x41 | 0x51 => {
+                extra_cycles += self.eor(&opcode.mode) as u8
+            }
+            0x85 | 0x95 | 0x8d | 0x9d | 0x99 | 0x81 | 0x91 => self.sta(&opcode.mode),
+            0xa2 | 0xa6 | 0xb6 | 0xae | 0xbe => extra_cycles += self.ldx(&opcode.mode) as u8,
+            0xa0 | 0xa4 | 0xb4 | 0xac | 0xbc => extra_cycles += self.ldy(&opcode.mode) as u8,
+            0x06 | 0x16 | 0x0e | 0x1e => self.asl(&opcode.mode),
+            0x46 | 0x56 | 0x4e | 0x5e => self.lsr(&opcode.mode),
+            0x26 | 0x36 | 0x2e | 0x3e => self.rol(&opcode.mode),
+            0x66 | 0x76 | 0x6e | 0x7e => self.ror(&opcode.mode),
+            0xe6 | 0xf6 | 0xee | 0xfe => self.inc(&opcode.mode),
+            0xc6 | 0xd6 | 0xce | 0xde => self.dec(&opcode.mode),
+            0x86 | 0x96 | 0x8e => self.stx(&opcode.mode),
+            0x84 | 0x94 | 0x8c => self.sty(&opcode.mode),
+            0x24 | 0x2c => self.bit(&opcode.mode),
+            0xc9 | 0xc5 | 0xd5 | 0xcd | 0xdd | 0xd9 | 0xc1 | 0xd1 => {
+                extra_cycles += self.cmp(&opcode.mode) as u8
+            }
+            0xe0 | 0xe4 | 0xec => extra_cycles += self.cpx(&opcode.mode) as u8,
+            0xc0 | 0xc4 | 0xcc => extra_cycles += self.cpy(&opcode.mode) as u8,
+            0x0a => self.asl_accumulator(),
+            0x4a => self.lsr_accumulator(),
+            0x2a => self.rol_accumulator(),
+            0x6a => self.ror_accumulator(),
+            0xe8 => self.inx(),
+            0xc8 => self.iny(),
+            0xca => self.dex(),
+            0x88 => self.dey(),
+            0xaa => self.tax(),
+            0x8a => self.txa(),
+            0xa8 => self.tay(),
+            0x98 => self.tya(),
+            0xba => self.tsx(),
+            0x9a => self.txs(),
+            0x18 => self.clc(),
+            0x38 => self.sec(),
+            0xd8 => self.cld(),
+            0xf8 => self.sed(),
+            0x58 => self.cli(),
+            0x78 => self.sei(),
+            0xb8 => self.clv(),
+            0x48 => self.pha(),
+            0x68 => self.pla(),
+            0x08 => self.php(),
+            0x28 => self.plp(),
+            0x4c => self.jmp_absolute(),
+            0x6c => self.jmp_indirect(),
+            0x20 => self.jsr(),
+            0x60 => self.rts(),
+            0x40 => self.rti(),
+            0xb0 => extra_cycles += self.branch(self.status.contains(CpuFlags::CARRY)),
+            0x90 => extra_cycles += self.branch(!self.status.contains(CpuFlags::CARRY)),
+            0xf0 => extra_cycles += self.branch(self.status.contains(CpuFlags::ZERO)),
+            0xd0 => extra_cycles += self.branch(!self.status.contains(CpuFlags::ZERO)),
+            0x30 => extra_cycles += self.branch(self.status.contains(CpuFlags::NEGATIVE)),
+            0x10 => extra_cycles += self.branch(!self.status.contains(CpuFlags::NEGATIVE)),
+            0x70 => extra_cycles += self.branch(self.status.contains(CpuFlags::OVERFLOW)),
+            0x50 => extra_cycles += self.branch(!self.status.contains(CpuFlags::OVERFLOW)),
+            0xea => { /* nop */ }
+            0x00 => self.brk(),
+            _ => todo!(),
+        }
+
+        if program_counter_state == self.program_counter {
+            self.program_counter += (opcode.len - 1) as u16;
         }
+
+        let total_cycles = INST_CYCLES[code as usize] + extra_cycles;
+        self.cycles += total_cycles as u64;
+        total_cycles
     }
 
     pub fn reset(&mut self) {
@@ -362,7 +828,9 @@ impl CPU {
     }
 
     pub fn load(&mut self, program: Vec<u8>) {
-        self.memory[0x8000..(0x8000 + program.len())].copy_from_slice(&program[..]);
+        for (i, &byte) in program.iter().enumerate() {
+            self.mem_write(0x8000 + i as u16, byte);
+        }
         self.mem_write_u16(0xFFFC, 0x8000);
     }
 
@@ -372,50 +840,242 @@ impl CPU {
         self.run();
     }
 
-    fn get_operand_address(&self, mode: &AddressingMode) -> u16 {
+    /// Serializes every register plus the full bus memory image behind a
+    /// small versioned header, so a future field addition can keep reading
+    /// old blobs instead of misinterpreting their bytes.
+    pub fn save_state(&self) -> Vec<u8> {
+        let mut buf = vec![
+            SAVE_STATE_VERSION,
+            self.register_a,
+            self.register_x,
+            self.register_y,
+            self.status.bits(),
+            self.stack_pointer,
+            self.nmi_pending as u8,
+        ];
+        buf.extend_from_slice(&self.program_counter.to_le_bytes());
+        buf.extend_from_slice(&self.cycles.to_le_bytes());
+        buf.extend_from_slice(&self.bus.snapshot());
+        buf
+    }
+
+    /// Restores a blob produced by `save_state`, rejecting a mismatched
+    /// version or a buffer whose length isn't exactly the header plus the
+    /// memory image instead of panicking on it.
+    pub fn load_state(&mut self, data: &[u8]) -> Result<(), StateError> {
+        const HEADER_LEN: usize = 1 + 1 + 1 + 1 + 1 + 1 + 1 + 2 + 8;
+
+        if data.is_empty() {
+            return Err(StateError::Truncated);
+        }
+        if data[0] != SAVE_STATE_VERSION {
+            return Err(StateError::UnsupportedVersion(data[0]));
+        }
+        if data.len() < HEADER_LEN {
+            return Err(StateError::Truncated);
+        }
+
+        let memory = &data[HEADER_LEN..];
+        if memory.len() != self.bus.snapshot().len() {
+            return Err(StateError::Truncated);
+        }
+
+        self.register_a = data[1];
+        self.register_x = data[2];
+        self.register_y = data[3];
+        self.status = CpuFlags::from_bits_truncate(data[4]);
+        self.stack_pointer = data[5];
+        self.nmi_pending = data[6] != 0;
+        self.program_counter = u16::from_le_bytes([data[7], data[8]]);
+        self.cycles = u64::from_le_bytes(data[9..17].try_into().unwrap());
+        self.bus.restore(memory);
+        Ok(())
+    }
+
+    /// Dumps the battery-backed cartridge RAM window (0x6000-0x7FFF) for a
+    /// `.sav`-style blob that outlives a single session.
+    pub fn save_battery_ram(&self) -> Vec<u8> {
+        (BATTERY_RAM_START..=BATTERY_RAM_END)
+            .map(|addr| self.mem_read(addr))
+            .collect()
+    }
+
+    /// Restores a blob produced by `save_battery_ram` into the same window.
+    pub fn load_battery_ram(&mut self, data: &[u8]) -> Result<(), StateError> {
+        let expected = (BATTERY_RAM_END - BATTERY_RAM_START + 1) as usize;
+        if data.len() != expected {
+            return Err(StateError::Truncated);
+        }
+        for (i, &byte) in data.iter().enumerate() {
+            self.mem_write(BATTERY_RAM_START + i as u16, byte);
+        }
+        Ok(())
+    }
+
+    /// Resolves `mode`'s effective address and whether reading/writing it
+    /// crosses a page boundary, i.e. the high byte of the un-indexed base
+    /// differs from the high byte of the indexed address. Only
+    /// `Absolute_X`/`Absolute_Y`/`Indirect_Y` can ever report a crossing;
+    /// every other mode always reports `false`.
+    fn get_operand_address(&self, mode: &AddressingMode) -> (u16, bool) {
         match mode {
-            AddressingMode::Immediate => self.program_counter,
-            AddressingMode::ZeroPage => self.mem_read(self.program_counter) as u16,
-            AddressingMode::Absolute => self.mem_read_u16(self.program_counter),
+            AddressingMode::Immediate => (self.program_counter, false),
+            AddressingMode::ZeroPage => (self.mem_read(self.program_counter) as u16, false),
+            AddressingMode::Absolute => (self.mem_read_u16(self.program_counter), false),
             AddressingMode::ZeroPage_X => {
                 let pos = self.mem_read(self.program_counter);
                 let addr = pos.wrapping_add(self.register_x) as u16;
-                addr
+                (addr, false)
             }
             AddressingMode::ZeroPage_Y => {
                 let pos = self.mem_read(self.program_counter);
                 let addr = pos.wrapping_add(self.register_y) as u16;
-                addr
+                (addr, false)
             }
             AddressingMode::Absolute_X => {
                 let base = self.mem_read_u16(self.program_counter);
                 let addr = base.wrapping_add(self.register_x as u16);
-                addr
+                (addr, base & 0xFF00 != addr & 0xFF00)
             }
             AddressingMode::Absolute_Y => {
                 let base = self.mem_read_u16(self.program_counter);
                 let addr = base.wrapping_add(self.register_y as u16);
-                addr
+                (addr, base & 0xFF00 != addr & 0xFF00)
             }
             AddressingMode::Indirect_X => {
                 let base = self.mem_read(self.program_counter);
                 let ptr: u8 = (base as u8).wrapping_add(self.register_x);
                 let lo = self.mem_read(ptr as u16);
                 let hi = self.mem_read(ptr.wrapping_add(1) as u16);
-                (hi as u16) << 8 | (lo as u16)
+                ((hi as u16) << 8 | (lo as u16), false)
             }
             AddressingMode::Indirect_Y => {
                 let base = self.mem_read(self.program_counter);
-                let ptr: u8 = (base as u8).wrapping_add(self.register_y);
-                let lo = self.mem_read(ptr as u16);
-                let hi = self.mem_read(ptr.wrapping_add(1) as u16);
-                (hi as u16) << 8 | (lo as u16)
+                let lo = self.mem_read(base as u16);
+                let hi = self.mem_read((base as u8).wrapping_add(1) as u16);
+                let deref_base = (hi as u16) << 8 | (lo as u16);
+                let addr = deref_base.wrapping_add(self.register_y as u16);
+                (addr, deref_base & 0xFF00 != addr & 0xFF00)
             }
             AddressingMode::NoneAddressing => {
                 panic!("mode {:?} is not supported", mode);
             }
         }
     }
+
+    /// Renders `opcode`'s operand, resolved against the bytes following it at
+    /// `instr_addr`, in the usual 6502-assembler notation (`$10,X`, `#$10`,
+    /// `($10,X)`, ...). The 8 relative branches and JMP indirect are special
+    /// cased since their operand isn't one of `AddressingMode`'s variants.
+    fn format_operand(&self, instr_addr: u16, opcode: &opcodes::OpCode) -> String {
+        match opcode.code {
+            0x90 | 0xb0 | 0xf0 | 0xd0 | 0x30 | 0x10 | 0x70 | 0x50 => {
+                let offset = self.mem_read(instr_addr.wrapping_add(1)) as i8;
+                let target = instr_addr.wrapping_add(2).wrapping_add(offset as u16);
+                format!("${:04X}", target)
+            }
+            0x6c => format!("(${:04X})", self.mem_read_u16(instr_addr.wrapping_add(1))),
+            0x4c | 0x20 => format!("${:04X}", self.mem_read_u16(instr_addr.wrapping_add(1))),
+            _ => match opcode.mode {
+                AddressingMode::Immediate => {
+                    format!("#${:02X}", self.mem_read(instr_addr.wrapping_add(1)))
+                }
+                AddressingMode::ZeroPage => {
+                    format!("${:02X}", self.mem_read(instr_addr.wrapping_add(1)))
+                }
+                AddressingMode::ZeroPage_X => {
+                    format!("${:02X},X", self.mem_read(instr_addr.wrapping_add(1)))
+                }
+                AddressingMode::ZeroPage_Y => {
+                    format!("${:02X},Y", self.mem_read(instr_addr.wrapping_add(1)))
+                }
+                AddressingMode::Absolute => {
+                    format!("${:04X}", self.mem_read_u16(instr_addr.wrapping_add(1)))
+                }
+                AddressingMode::Absolute_X => {
+                    format!("${:04X},X", self.mem_read_u16(instr_addr.wrapping_add(1)))
+                }
+                AddressingMode::Absolute_Y => {
+                    format!("${:04X},Y", self.mem_read_u16(instr_addr.wrapping_add(1)))
+                }
+                AddressingMode::Indirect_X => {
+                    format!("(${:02X},X)", self.mem_read(instr_addr.wrapping_add(1)))
+                }
+                AddressingMode::Indirect_Y => {
+                    format!("(${:02X}),Y", self.mem_read(instr_addr.wrapping_add(1)))
+                }
+                AddressingMode::NoneAddressing => String::new(),
+            },
+        }
+    }
+
+    /// Walks `count` instructions from `start`, decoding each via
+    /// `opcodes::OPCODES_MAP` into its address and a formatted
+    /// `"MNEMONIC operand"` string. A byte that isn't a recognized opcode is
+    /// rendered as a `.byte` directive so disassembly can keep walking
+    /// through data embedded in code.
+    pub fn disassemble(&self, start: u16, count: usize) -> Vec<(u16, String)> {
+        let mut result = Vec::with_capacity(count);
+        let mut addr = start;
+
+        for _ in 0..count {
+            let code = self.mem_read(addr);
+            let opcode = match opcodes::OPCODES_MAP.get(&code) {
+                Some(opcode) => opcode,
+                None => {
+                    result.push((addr, format!(".byte ${:02X}", code)));
+                    addr = addr.wrapping_add(1);
+                    continue;
+                }
+            };
+
+            let operand = self.format_operand(addr, opcode);
+            let text = if operand.is_empty() {
+                opcode.mnemonic.to_string()
+            } else {
+                format!("{} {}", opcode.mnemonic, operand)
+            };
+            result.push((addr, text));
+            addr = addr.wrapping_add(opcode.len as u16);
+        }
+
+        result
+    }
+
+    /// Produces a nintendulator-style trace line - PC, raw opcode bytes,
+    /// disassembly, and register dump - for diffing against known-good CPU
+    /// logs. Intended to be called before each `step`.
+    pub fn trace(&self) -> String {
+        let code = self.mem_read(self.program_counter);
+        let len = opcodes::OPCODES_MAP.get(&code).map_or(1, |op| op.len);
+
+        let mut raw_bytes = String::new();
+        for offset in 0..len as u16 {
+            raw_bytes.push_str(&format!(
+                "{:02X} ",
+                self.mem_read(self.program_counter.wrapping_add(offset))
+            ));
+        }
+
+        let disassembly = self
+            .disassemble(self.program_counter, 1)
+            .into_iter()
+            .next()
+            .map(|(_, text)| text)
+            .unwrap_or_default();
+
+        format!(
+            "{:04X}  {:<9}{:<32}A:{:02X} X:{:02X} Y:{:02X} P:{:02X} SP:{:02X}",
+            self.program_counter,
+            raw_bytes,
+            disassembly,
+            self.register_a,
+            self.register_x,
+            self.register_y,
+            self.status.bits(),
+            self.stack_pointer,
+        )
+    }
 }
 
 #[cfg(test)]
@@ -480,6 +1140,29 @@ mod test {
         assert!(cpu.status.contains(CpuFlags::NEGATIVE));
     }
 
+    #[test]
+    fn test_adc_decimal_mode() {
+        let mut cpu = CPU::new();
+        cpu.load(vec![0xa9, 0x58, 0x69, 0x46, 0x00]);
+        cpu.reset();
+        cpu.status.insert(CpuFlags::DECIMAL_MODE);
+        cpu.run();
+        assert_eq!(cpu.register_a, 0x04);
+        assert!(cpu.status.contains(CpuFlags::CARRY));
+    }
+
+    #[test]
+    fn test_sbc_decimal_mode() {
+        let mut cpu = CPU::new();
+        cpu.load(vec![0xa9, 0x00, 0xe9, 0x01, 0x00]);
+        cpu.reset();
+        cpu.status.insert(CpuFlags::DECIMAL_MODE);
+        cpu.status.insert(CpuFlags::CARRY);
+        cpu.run();
+        assert_eq!(cpu.register_a, 0x99);
+        assert!(!cpu.status.contains(CpuFlags::CARRY));
+    }
+
     #[test]
     fn test_asl() {
         let mut cpu = CPU::new();
@@ -526,4 +1209,301 @@ mod test {
         assert!(!cpu.status.contains(CpuFlags::OVERFLOW));
         assert!(!cpu.status.contains(CpuFlags::NEGATIVE));
     }
+
+    #[test]
+    fn test_inc_dec_memory() {
+        let mut cpu = CPU::new();
+        cpu.mem_write(0x10, 0xff);
+        cpu.load_and_run(vec![0xe6, 0x10, 0x00]);
+        assert_eq!(cpu.mem_read(0x10), 0x00);
+        assert!(cpu.status.contains(CpuFlags::ZERO));
+        cpu.mem_write(0x10, 0x00);
+        cpu.load_and_run(vec![0xc6, 0x10, 0x00]);
+        assert_eq!(cpu.mem_read(0x10), 0xff);
+        assert!(cpu.status.contains(CpuFlags::NEGATIVE));
+    }
+
+    #[test]
+    fn test_inx_iny_overflow() {
+        let mut cpu = CPU::new();
+        cpu.load_and_run(vec![0xa2, 0xff, 0xe8, 0xe8, 0x00]);
+        assert_eq!(cpu.register_x, 1);
+        cpu.load_and_run(vec![0xa0, 0xff, 0xc8, 0xc8, 0x00]);
+        assert_eq!(cpu.register_y, 1);
+    }
+
+    #[test]
+    fn test_dex_dey() {
+        let mut cpu = CPU::new();
+        cpu.load_and_run(vec![0xa2, 0x01, 0xca, 0x00]);
+        assert_eq!(cpu.register_x, 0x00);
+        assert!(cpu.status.contains(CpuFlags::ZERO));
+        cpu.load_and_run(vec![0xa0, 0x00, 0x88, 0x00]);
+        assert_eq!(cpu.register_y, 0xff);
+        assert!(cpu.status.contains(CpuFlags::NEGATIVE));
+    }
+
+    #[test]
+    fn test_cmp() {
+        let mut cpu = CPU::new();
+        cpu.load_and_run(vec![0xa9, 0x10, 0xc9, 0x10, 0x00]);
+        assert!(cpu.status.contains(CpuFlags::CARRY));
+        assert!(cpu.status.contains(CpuFlags::ZERO));
+        cpu.load_and_run(vec![0xa9, 0x05, 0xc9, 0x10, 0x00]);
+        assert!(!cpu.status.contains(CpuFlags::CARRY));
+        assert!(!cpu.status.contains(CpuFlags::ZERO));
+    }
+
+    #[test]
+    fn test_cpx_cpy() {
+        let mut cpu = CPU::new();
+        cpu.load_and_run(vec![0xa2, 0x10, 0xe0, 0x05, 0x00]);
+        assert!(cpu.status.contains(CpuFlags::CARRY));
+        cpu.load_and_run(vec![0xa0, 0x05, 0xc0, 0x10, 0x00]);
+        assert!(!cpu.status.contains(CpuFlags::CARRY));
+    }
+
+    #[test]
+    fn test_flag_ops() {
+        let mut cpu = CPU::new();
+        cpu.load_and_run(vec![0x38, 0x00]);
+        assert!(cpu.status.contains(CpuFlags::CARRY));
+        cpu.load(vec![0x18, 0x00]);
+        cpu.reset();
+        cpu.status.insert(CpuFlags::CARRY);
+        cpu.run();
+        assert!(!cpu.status.contains(CpuFlags::CARRY));
+        cpu.load_and_run(vec![0xf8, 0x00]);
+        assert!(cpu.status.contains(CpuFlags::DECIMAL_MODE));
+        cpu.load(vec![0xd8, 0x00]);
+        cpu.reset();
+        cpu.status.insert(CpuFlags::DECIMAL_MODE);
+        cpu.run();
+        assert!(!cpu.status.contains(CpuFlags::DECIMAL_MODE));
+        cpu.load_and_run(vec![0x78, 0x00]);
+        assert!(cpu.status.contains(CpuFlags::INTERRUPT_DISABLE));
+        cpu.load(vec![0x58, 0x00]);
+        cpu.reset();
+        cpu.status.insert(CpuFlags::INTERRUPT_DISABLE);
+        cpu.step();
+        assert!(!cpu.status.contains(CpuFlags::INTERRUPT_DISABLE));
+        cpu.load(vec![0xb8, 0x00]);
+        cpu.reset();
+        cpu.status.insert(CpuFlags::OVERFLOW);
+        cpu.run();
+        assert!(!cpu.status.contains(CpuFlags::OVERFLOW));
+    }
+
+    #[test]
+    fn test_pha_pla() {
+        let mut cpu = CPU::new();
+        cpu.load_and_run(vec![0xa9, 0x42, 0x48, 0xa9, 0x00, 0x68, 0x00]);
+        assert_eq!(cpu.register_a, 0x42);
+        assert_eq!(cpu.stack_pointer, STACK_RESET.wrapping_sub(3));
+    }
+
+    #[test]
+    fn test_php_plp() {
+        let mut cpu = CPU::new();
+        cpu.load_and_run(vec![0x38, 0x08, 0x18, 0x28, 0x00]);
+        assert!(cpu.status.contains(CpuFlags::CARRY));
+    }
+
+    #[test]
+    fn test_tsx_txs() {
+        let mut cpu = CPU::new();
+        cpu.load_and_run(vec![0xba, 0x00]);
+        assert_eq!(cpu.register_x, STACK_RESET);
+        cpu.load(vec![0xa2, 0x80, 0x9a, 0x00]);
+        cpu.reset();
+        cpu.run();
+        assert_eq!(cpu.register_x, 0x80);
+        // TXS set SP to $80; the final BRK then pushed three bytes.
+        assert_eq!(cpu.stack_pointer, 0x80u8.wrapping_sub(3));
+    }
+
+    #[test]
+    fn test_jmp_absolute() {
+        let mut cpu = CPU::new();
+        cpu.load_and_run(vec![0x4c, 0x05, 0x80, 0x00, 0x00, 0xa9, 0x42, 0x00]);
+        assert_eq!(cpu.register_a, 0x42);
+    }
+
+    #[test]
+    fn test_jmp_indirect_page_wrap_bug() {
+        let mut cpu = CPU::new();
+        cpu.mem_write(0x30ff, 0x06);
+        cpu.mem_write(0x3000, 0x80);
+        cpu.mem_write(0x3100, 0xff);
+        cpu.load_and_run(vec![0x6c, 0xff, 0x30, 0x00, 0x00, 0x00, 0xa9, 0x42, 0x00]);
+        assert_eq!(cpu.register_a, 0x42);
+    }
+
+    #[test]
+    fn test_bne_taken_backward_runs_a_countdown_loop() {
+        let mut cpu = CPU::new();
+        // LDX #$03; loop: DEX; BNE loop; BRK
+        cpu.load_and_run(vec![0xa2, 0x03, 0xca, 0xd0, 0xfd, 0x00]);
+        assert_eq!(cpu.register_x, 0);
+    }
+
+    #[test]
+    fn test_bne_backward_page_cross_costs_two_extra_cycles() {
+        let mut cpu = CPU::new();
+        // DEX at $80FE, BNE $80FF back to $80FE (crosses from page $81 to $80).
+        cpu.mem_write(0x80fe, 0xca);
+        cpu.mem_write(0x80ff, 0xd0);
+        cpu.mem_write(0x8100, 0xfd);
+        cpu.mem_write_u16(0xFFFC, 0x80fe);
+        cpu.reset();
+        cpu.register_x = 2;
+        cpu.step();
+        assert_eq!(cpu.program_counter, 0x80ff);
+        let cycles = cpu.step();
+        assert_eq!(cpu.program_counter, 0x80fe);
+        assert_eq!(cycles, 2 + 2);
+    }
+
+    #[test]
+    fn test_jsr_rts() {
+        let mut cpu = CPU::new();
+        cpu.load_and_run(vec![
+            0x20, 0x06, 0x80, 0xa9, 0x01, 0x00, 0xa9, 0x42, 0x60,
+        ]);
+        assert_eq!(cpu.register_a, 0x01);
+    }
+
+    #[test]
+    fn test_brk_pushes_return_address_and_status() {
+        let mut cpu = CPU::new();
+        cpu.mem_write_u16(0xFFFE, 0x9000);
+        cpu.load_and_run(vec![0x00]);
+        assert_eq!(cpu.program_counter, 0x9000);
+        assert!(cpu.status.contains(CpuFlags::INTERRUPT_DISABLE));
+        let pushed_status = cpu.mem_read(STACK + cpu.stack_pointer.wrapping_add(1) as u16);
+        assert!(CpuFlags::from_bits_truncate(pushed_status).contains(CpuFlags::BREAK));
+        let return_addr = cpu.mem_read_u16(STACK + cpu.stack_pointer.wrapping_add(2) as u16);
+        assert_eq!(return_addr, 0x8002);
+    }
+
+    #[test]
+    fn test_nmi_polled_between_instructions() {
+        let mut cpu = CPU::new();
+        cpu.load(vec![0xea, 0x00]);
+        cpu.reset();
+        cpu.mem_write_u16(0xFFFA, 0x9000);
+        cpu.nmi_pending = true;
+        let cycles = cpu.step();
+        assert_eq!(cycles, 7);
+        assert_eq!(cpu.program_counter, 0x9000);
+        assert!(!cpu.nmi_pending);
+        assert!(cpu.status.contains(CpuFlags::INTERRUPT_DISABLE));
+    }
+
+    #[test]
+    fn test_irq_gated_by_interrupt_disable() {
+        let mut cpu = CPU::new();
+        cpu.load(vec![0xea, 0x00]);
+        cpu.reset();
+        cpu.mem_write_u16(0xFFFE, 0x9000);
+        cpu.status.insert(CpuFlags::INTERRUPT_DISABLE);
+        cpu.irq();
+        assert_ne!(cpu.program_counter, 0x9000);
+        cpu.status.remove(CpuFlags::INTERRUPT_DISABLE);
+        cpu.irq();
+        assert_eq!(cpu.program_counter, 0x9000);
+    }
+
+    #[test]
+    fn test_save_and_load_state_round_trip() {
+        let mut cpu = CPU::new();
+        cpu.mem_write(0x10, 0x55);
+        cpu.load_and_run(vec![0xa5, 0x10, 0xe8, 0x00]);
+        let blob = cpu.save_state();
+
+        let mut restored = CPU::new();
+        restored.load_state(&blob).unwrap();
+        assert_eq!(restored.register_a, cpu.register_a);
+        assert_eq!(restored.register_x, cpu.register_x);
+        assert_eq!(restored.status, cpu.status);
+        assert_eq!(restored.program_counter, cpu.program_counter);
+        assert_eq!(restored.stack_pointer, cpu.stack_pointer);
+        assert_eq!(restored.cycles, cpu.cycles);
+        assert_eq!(restored.mem_read(0x10), 0x55);
+    }
+
+    #[test]
+    fn test_load_state_rejects_bad_version_and_truncated_buffer() {
+        let mut cpu = CPU::new();
+        assert!(matches!(
+            cpu.load_state(&[]),
+            Err(StateError::Truncated)
+        ));
+        assert!(matches!(
+            cpu.load_state(&[0xff]),
+            Err(StateError::UnsupportedVersion(0xff))
+        ));
+        assert!(matches!(
+            cpu.load_state(&[SAVE_STATE_VERSION, 0x01]),
+            Err(StateError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn test_battery_ram_round_trip() {
+        let mut cpu = CPU::new();
+        cpu.mem_write(BATTERY_RAM_START, 0xab);
+        cpu.mem_write(BATTERY_RAM_END, 0xcd);
+        let blob = cpu.save_battery_ram();
+
+        let mut restored = CPU::new();
+        restored.load_battery_ram(&blob).unwrap();
+        assert_eq!(restored.mem_read(BATTERY_RAM_START), 0xab);
+        assert_eq!(restored.mem_read(BATTERY_RAM_END), 0xcd);
+        assert!(matches!(
+            restored.load_battery_ram(&[0x00]),
+            Err(StateError::Truncated)
+        ));
+    }
+
+    #[test]
+    fn test_disassemble() {
+        let mut cpu = CPU::new();
+        cpu.mem_write(0x8000, 0xa9);
+        cpu.mem_write(0x8001, 0x10);
+        cpu.mem_write(0x8002, 0xd0);
+        cpu.mem_write(0x8003, 0xfe);
+        cpu.mem_write(0x8004, 0x4c);
+        cpu.mem_write(0x8005, 0x00);
+        cpu.mem_write(0x8006, 0x90);
+
+        let lines = cpu.disassemble(0x8000, 3);
+        assert_eq!(lines[0], (0x8000, "LDA #$10".to_string()));
+        assert_eq!(lines[1], (0x8002, "BNE $8002".to_string()));
+        assert_eq!(lines[2], (0x8004, "JMP $9000".to_string()));
+    }
+
+    #[test]
+    fn test_disassemble_jmp_indirect_and_unknown_byte() {
+        let mut cpu = CPU::new();
+        cpu.mem_write(0x8000, 0x6c);
+        cpu.mem_write(0x8001, 0x00);
+        cpu.mem_write(0x8002, 0x90);
+        cpu.mem_write(0x8003, 0xff);
+
+        let lines = cpu.disassemble(0x8000, 2);
+        assert_eq!(lines[0], (0x8000, "JMP ($9000)".to_string()));
+        assert_eq!(lines[1], (0x8003, ".byte $FF".to_string()));
+    }
+
+    #[test]
+    fn test_trace() {
+        let mut cpu = CPU::new();
+        cpu.load_and_run(vec![0xa9, 0x10, 0x00]);
+        cpu.program_counter = 0x8000;
+        let line = cpu.trace();
+        assert!(line.starts_with("8000  A9 10"));
+        assert!(line.contains("LDA #$10"));
+        assert!(line.contains("A:10"));
+    }
 }